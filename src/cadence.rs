@@ -0,0 +1,279 @@
+//! Inference of how often images were actually published, from the images already in an
+//! archive. ECCC has changed the publication interval over the years (hourly in early years,
+//! every 10 minutes later), so a single interval cannot serve a long historical request.
+
+use crate::catalog::{self, Catalog};
+use crate::snapshot::hash_file;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// The interval planned for periods the archive says nothing about.
+const DEFAULT_INTERVAL_MINUTES: i64 = 60;
+
+/// The shortest interval ECCC has published at, used to probe months whose images were
+/// sampled too coarsely to show how often they were published.
+const FINEST_INTERVAL_MINUTES: i64 = 10;
+
+/// A run of consecutive calendar months sharing the same publication interval.
+pub struct Era {
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+    pub interval_minutes: i64,
+    /// Whether the images were requested more often than they were published. If not, every
+    /// request returned a new image and the images may have been published more often still.
+    pub resolved: bool,
+    /// Distinct images published.
+    pub images: usize,
+    /// Files in the archive, including repeats of the same image.
+    pub frames: usize,
+}
+
+/// The time and path of every image of a site in the catalog, grouped by image type and sorted
+/// by time.
+pub fn frame_paths<'a>(catalog: &'a Catalog, site: &str) -> BTreeMap<String, Vec<(DateTime<Utc>, &'a Path)>> {
+    let mut frames: BTreeMap<String, Vec<_>> = BTreeMap::new();
+
+    for (name, path) in catalog.frames() {
+        match catalog::parse_frame_name(name) {
+            Some((frame_site, image_type, time)) if frame_site == site => {
+                frames.entry(image_type.to_owned()).or_default().push((time, path));
+            },
+            _ => {},
+        }
+    }
+
+    for list in frames.values_mut() {
+        list.sort();
+    }
+    frames
+}
+
+/// Pairs each image time with the SHA-256 of its contents.
+pub fn hash_frames(frames: &[(DateTime<Utc>, &Path)]) -> io::Result<Vec<(DateTime<Utc>, String)>> {
+    frames.iter()
+        .map(|(time, path)| Ok((*time, hash_file(path)?.hash)))
+        .collect()
+}
+
+/// Splits hashed images, sorted by time, into eras.
+///
+/// File names only say when an image was requested; the server answers with the nearest image
+/// it has, so a run of identical consecutive images is one publication. Each calendar month is
+/// assigned the interval between its publications, and neighbouring months with the same
+/// interval are merged. A month with a single publication joins the era before it.
+pub fn infer(frames: &[(DateTime<Utc>, String)]) -> Vec<Era> {
+    let mut eras: Vec<Era> = Vec::new();
+    let mut month_start = 0;
+
+    while month_start < frames.len() {
+        let month = month_of(frames[month_start].0);
+        let month_end = frames[month_start..].iter()
+            .position(|(time, _)| month_of(*time) != month)
+            .map_or(frames.len(), |offset| month_start + offset);
+        let samples = &frames[month_start..month_end];
+        let first = samples[0].0;
+        let last = samples[samples.len() - 1].0;
+
+        let published = publications(samples);
+        let sampling = shortest_gap(samples);
+        let cadence = publication_interval(&published)
+            .map(|interval| (interval, interval <= FINEST_INTERVAL_MINUTES || sampling.is_some_and(|sampling| sampling < interval)));
+
+        let continues = match (cadence, eras.last()) {
+            (Some((interval, resolved)), Some(era)) => era.interval_minutes == interval && era.resolved == resolved,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+
+        if continues {
+            let era = eras.last_mut().unwrap();
+            era.last = last;
+            era.images += published.len();
+            era.frames += samples.len();
+        } else if let Some((interval_minutes, resolved)) = cadence {
+            eras.push(Era { first, last, interval_minutes, resolved, images: published.len(), frames: samples.len() });
+        }
+
+        month_start = month_end;
+    }
+
+    eras
+}
+
+/// The minutes to step forward from `time` when planning requests that started on `start`.
+///
+/// Resolved eras use their interval. The interval of an unresolved era only bounds how far apart
+/// publications were, so it is never planned coarser than hourly, and the month is probed at the
+/// finest interval on its first planned day so that the next inference can resolve it. Months the
+/// archive says nothing about, and intervals that would drift off the hourly grid, are planned
+/// hourly.
+pub fn planned_interval(eras: &[Era], time: DateTime<Utc>, start: NaiveDate) -> i64 {
+    let interval = match era_at(eras, time) {
+        Some(era) if era.resolved => era.interval_minutes,
+        Some(era) => {
+            let day = time.date_naive();
+            if day == std::cmp::max(start, day.with_day(1).unwrap()) {
+                return FINEST_INTERVAL_MINUTES;
+            }
+            era.interval_minutes.min(DEFAULT_INTERVAL_MINUTES)
+        },
+        None => DEFAULT_INTERVAL_MINUTES,
+    };
+
+    if DEFAULT_INTERVAL_MINUTES % interval == 0 {
+        interval
+    } else {
+        DEFAULT_INTERVAL_MINUTES
+    }
+}
+
+/// How far apart images around `time` are expected to have been published at most: the
+/// interval of its era, or hourly where the archive says nothing. Consecutive requests further
+/// apart than this should each return a different image.
+pub fn expected_interval(eras: &[Era], time: DateTime<Utc>) -> i64 {
+    era_at(eras, time).map_or(DEFAULT_INTERVAL_MINUTES, |era| era.interval_minutes)
+}
+
+/// The era covering the month of `time`, if any.
+fn era_at(eras: &[Era], time: DateTime<Utc>) -> Option<&Era> {
+    let month = month_of(time);
+    eras.iter().find(|era| month_of(era.first) <= month && month <= month_of(era.last))
+}
+
+fn month_of(time: DateTime<Utc>) -> (i32, u32) {
+    (time.year(), time.month())
+}
+
+/// The times at which the content changes, i.e. the first of each run of identical images.
+fn publications(samples: &[(DateTime<Utc>, String)]) -> Vec<DateTime<Utc>> {
+    samples.iter().enumerate()
+        .filter(|(index, (_, hash))| *index == 0 || samples[index - 1].1 != *hash)
+        .map(|(_, (time, _))| *time)
+        .collect()
+}
+
+/// The shortest gap in minutes between requested images.
+fn shortest_gap(samples: &[(DateTime<Utc>, String)]) -> Option<i64> {
+    samples.windows(2).map(|pair| (pair[1].0 - pair[0].0).num_minutes()).filter(|minutes| *minutes > 0).min()
+}
+
+/// The shortest gap in minutes between publications that occurs at least twice, or the
+/// shortest gap if none repeats. Missing images only ever lengthen gaps, and asking for a
+/// repeat keeps one late image from passing for a faster interval.
+fn publication_interval(publications: &[DateTime<Utc>]) -> Option<i64> {
+    let mut counts = BTreeMap::new();
+    for pair in publications.windows(2) {
+        *counts.entry((pair[1] - pair[0]).num_minutes()).or_insert(0) += 1;
+    }
+    counts.iter()
+        .find(|(_, count)| **count >= 2)
+        .or_else(|| counts.iter().next())
+        .map(|(minutes, _)| *minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Hashed images at the given minutes past midnight on the first of a month, named by their
+    /// content.
+    fn frames(year: i32, month: u32, images: &[(i64, &str)]) -> Vec<(DateTime<Utc>, String)> {
+        let midnight = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).unwrap();
+        images.iter()
+            .map(|(minutes, content)| (midnight + chrono::Duration::minutes(*minutes), content.to_string()))
+            .collect()
+    }
+
+    fn at(month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2021, month, day, hour, 0, 0).unwrap()
+    }
+
+    fn day(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, month, day).unwrap()
+    }
+
+    #[test]
+    fn repeated_images_are_one_publication() {
+        let eras = infer(&frames(2020, 12, &[(0, "a"), (10, "a"), (20, "a"), (60, "b"), (70, "b"), (120, "c")]));
+        assert_eq!(eras.len(), 1);
+        assert_eq!(eras[0].interval_minutes, 60);
+        assert!(eras[0].resolved);
+        assert_eq!((eras[0].images, eras[0].frames), (3, 6));
+    }
+
+    #[test]
+    fn images_changing_on_every_request_are_unresolved() {
+        let eras = infer(&frames(2021, 2, &[(0, "a"), (60, "b"), (120, "c")]));
+        assert_eq!(eras[0].interval_minutes, 60);
+        assert!(!eras[0].resolved);
+
+        let eras = infer(&frames(2021, 1, &[(0, "a"), (10, "b"), (20, "c")]));
+        assert_eq!(eras[0].interval_minutes, 10);
+        assert!(eras[0].resolved);
+    }
+
+    #[test]
+    fn a_single_late_image_does_not_set_the_interval() {
+        let eras = infer(&frames(2020, 12, &[(0, "a"), (10, "a"), (60, "b"), (70, "c"), (120, "d"), (180, "e"), (240, "f")]));
+        assert_eq!(eras[0].interval_minutes, 60);
+    }
+
+    #[test]
+    fn months_are_merged_into_eras() {
+        let mut hashed = frames(2020, 11, &[(0, "a"), (60, "b"), (120, "c")]);
+        hashed.extend(frames(2020, 12, &[(0, "d"), (60, "e"), (120, "f")]));
+        hashed.extend(frames(2021, 1, &[(0, "g"), (10, "h"), (20, "i")]));
+        let eras = infer(&hashed);
+        assert_eq!(eras.len(), 2);
+        assert_eq!((eras[0].first, eras[0].last), (hashed[0].0, hashed[5].0));
+        assert_eq!(eras[1].interval_minutes, 10);
+    }
+
+    #[test]
+    fn unresolved_months_are_probed_on_their_first_planned_day() {
+        let eras = infer(&frames(2021, 2, &[(0, "a"), (60, "b"), (120, "c")]));
+        assert_eq!(planned_interval(&eras, at(2, 3, 5), day(2, 3)), FINEST_INTERVAL_MINUTES);
+        assert_eq!(planned_interval(&eras, at(2, 4, 5), day(2, 3)), 60);
+        assert_eq!(planned_interval(&eras, at(3, 1, 5), day(2, 3)), DEFAULT_INTERVAL_MINUTES);
+    }
+
+    #[test]
+    fn sparse_months_are_planned_no_coarser_than_hourly() {
+        let eras = infer(&frames(2021, 3, &[(0, "a"), (4 * 24 * 60, "b")]));
+        assert_eq!((eras[0].interval_minutes, eras[0].resolved), (4 * 24 * 60, false));
+        assert_eq!(planned_interval(&eras, at(3, 2, 0), day(3, 1)), 60);
+        assert_eq!(planned_interval(&eras, at(3, 6, 0), day(3, 1)), 60);
+    }
+
+    #[test]
+    fn intervals_off_the_hourly_grid_are_planned_hourly() {
+        let samples: Vec<(i64, String)> = (0..24).map(|index| (index * 10, (index / 9).to_string())).collect();
+        let samples: Vec<(i64, &str)> = samples.iter().map(|(minutes, content)| (*minutes, content.as_str())).collect();
+        let eras = infer(&frames(2021, 3, &samples));
+        assert_eq!((eras[0].interval_minutes, eras[0].resolved), (90, true));
+        assert_eq!(planned_interval(&eras, at(3, 2, 0), day(3, 1)), 60);
+    }
+
+    #[test]
+    fn gappy_months_are_probed_until_resolved() {
+        // Every other request of a 10 minute archive failed, so only every 20 minutes is stored.
+        let gappy: Vec<(i64, String)> = (0..18).map(|index| (index * 20, index.to_string())).collect();
+        let gappy: Vec<(i64, &str)> = gappy.iter().map(|(minutes, content)| (*minutes, content.as_str())).collect();
+        let eras = infer(&frames(2021, 3, &gappy));
+        assert_eq!((eras[0].interval_minutes, eras[0].resolved), (20, false));
+        assert_eq!(planned_interval(&eras, at(3, 1, 0), day(3, 1)), FINEST_INTERVAL_MINUTES);
+
+        // The probe day requests the missing slots, after which every slot is planned.
+        let filled: Vec<(i64, String)> = (0..18).map(|index| (index * 20 + 10, format!("filled {}", index))).collect();
+        let filled: Vec<(i64, &str)> = filled.iter().map(|(minutes, content)| (*minutes, content.as_str())).collect();
+        let mut probed = frames(2021, 3, &gappy);
+        probed.extend(frames(2021, 3, &filled));
+        probed.sort();
+        let eras = infer(&probed);
+        assert_eq!((eras[0].interval_minutes, eras[0].resolved), (10, true));
+        assert_eq!(planned_interval(&eras, at(3, 20, 0), day(3, 1)), FINEST_INTERVAL_MINUTES);
+    }
+}
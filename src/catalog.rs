@@ -0,0 +1,81 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A deduplicated view of an archive whose frames are spread over several directories, such
+/// as one volume per year. Each frame file name maps to its path on the first volume holding it.
+pub struct Catalog {
+    frames: HashMap<String, PathBuf>,
+}
+
+impl Catalog {
+    /// Walks each volume in order. A volume that does not exist is an error rather than an empty
+    /// volume, since an unmounted disk would otherwise look like an archive missing all its frames.
+    pub fn scan(volumes: &[PathBuf]) -> io::Result<Catalog> {
+        let mut frames = HashMap::new();
+
+        for volume in volumes {
+            if !volume.is_dir() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("Archive volume '{}' does not exist or is not a directory.", volume.display())));
+            }
+            for entry in std::fs::read_dir(volume)? {
+                let entry = entry?;
+                if !entry.file_type()?.is_file() {
+                    continue;
+                }
+                if let Some(name) = entry.file_name().to_str() {
+                    frames.entry(name.to_owned()).or_insert_with(|| entry.path());
+                }
+            }
+        }
+
+        Ok(Catalog { frames })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.frames.contains_key(name)
+    }
+
+    /// Every frame in the catalog with its path, in no particular order.
+    pub fn frames(&self) -> impl Iterator<Item = (&str, &Path)> {
+        self.frames.iter().map(|(name, path)| (name.as_str(), path.as_path()))
+    }
+}
+
+/// Splits a frame file name, `<site>_<image type>_<YYYY-MM-DDTHH-MM>.gif`, into its site, image
+/// type and time. Anything else in a volume, such as an outages file or a snapshot manifest, is
+/// not a frame.
+pub fn parse_frame_name(name: &str) -> Option<(&str, &str, DateTime<Utc>)> {
+    let rest = name.strip_suffix(".gif")?;
+    let (site, rest) = rest.split_once('_')?;
+    // Image types contain underscores, the timestamp never does.
+    let (image_type, time) = rest.rsplit_once('_')?;
+    if site.is_empty() || image_type.is_empty() {
+        return None;
+    }
+    let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H-%M").ok()?;
+    Some((site, image_type, DateTime::from_naive_utc_and_offset(time, Utc)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn frame_names_are_split_into_site_image_type_and_time() {
+        assert_eq!(
+            parse_frame_name("ATL_COMP_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T23-50.gif"),
+            Some(("ATL", "COMP_PRECIPET_RAIN_WEATHEROFFICE", Utc.with_ymd_and_hms(2021, 1, 1, 23, 50, 0).unwrap()))
+        );
+    }
+
+    #[test]
+    fn other_files_are_not_frames() {
+        assert_eq!(parse_frame_name("outages.csv"), None);
+        assert_eq!(parse_frame_name("snapshot.gif"), None);
+        assert_eq!(parse_frame_name("ATL_2021-01-01T23-50.gif"), None);
+        assert_eq!(parse_frame_name("ATL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01.gif"), None);
+    }
+}
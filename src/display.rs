@@ -0,0 +1,94 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+const FORMAT: &str = "%Y-%m-%d %H:%M %:z";
+
+/// The timezone timestamps are shown in. Storage (file names, requests) always stays in UTC.
+#[derive(Clone, Copy, Debug)]
+pub enum DisplayTimezone {
+    /// An IANA timezone such as `America/Halifax`, which follows daylight saving time.
+    Named(Tz),
+    /// A fixed offset from UTC, the same all year round.
+    Offset(FixedOffset),
+}
+
+impl FromStr for DisplayTimezone {
+    type Err = String;
+
+    /// Accepts an IANA name such as `America/Halifax` or `UTC`, or an offset such as `-04:00`,
+    /// `-0400` or `-4`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(timezone) = s.parse::<Tz>() {
+            return Ok(DisplayTimezone::Named(timezone));
+        }
+
+        let invalid = || format!("Invalid display timezone '{}'; expected a timezone name such as America/Halifax or an offset such as -04:00.", s);
+
+        let (sign, offset) = match s.chars().next() {
+            Some('+') => (1, &s[1..]),
+            Some('-') => (-1, &s[1..]),
+            _ => return Err(invalid()),
+        };
+        let digits: String = offset.chars().filter(|c| *c != ':').collect();
+        let (hours, minutes) = match digits.len() {
+            1 | 2 => (digits.as_str(), "0"),
+            4 => digits.split_at(2),
+            _ => return Err(invalid()),
+        };
+        let hours = hours.parse::<i32>().map_err(|_| invalid())?;
+        let minutes = minutes.parse::<i32>().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+            .map(DisplayTimezone::Offset)
+            .ok_or_else(invalid)
+    }
+}
+
+impl DisplayTimezone {
+    pub fn format(&self, time: DateTime<Utc>) -> String {
+        match self {
+            DisplayTimezone::Named(timezone) => time.with_timezone(timezone).format(FORMAT).to_string(),
+            DisplayTimezone::Offset(offset) => time.with_timezone(offset).format(FORMAT).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn format(timezone: &str, time: DateTime<Utc>) -> String {
+        timezone.parse::<DisplayTimezone>().unwrap().format(time)
+    }
+
+    #[test]
+    fn named_timezones_follow_daylight_saving_time() {
+        assert_eq!(format("America/Halifax", Utc.with_ymd_and_hms(2021, 1, 15, 12, 0, 0).unwrap()), "2021-01-15 08:00 -04:00");
+        assert_eq!(format("America/Halifax", Utc.with_ymd_and_hms(2021, 7, 15, 12, 0, 0).unwrap()), "2021-07-15 09:00 -03:00");
+        assert_eq!(format("America/St_Johns", Utc.with_ymd_and_hms(2021, 1, 15, 12, 0, 0).unwrap()), "2021-01-15 08:30 -03:30");
+        assert_eq!(format("UTC", Utc.with_ymd_and_hms(2021, 7, 15, 12, 0, 0).unwrap()), "2021-07-15 12:00 +00:00");
+    }
+
+    #[test]
+    fn offsets_are_fixed() {
+        let summer = Utc.with_ymd_and_hms(2021, 7, 15, 12, 0, 0).unwrap();
+        assert_eq!(format("-04:00", summer), "2021-07-15 08:00 -04:00");
+        assert_eq!(format("-0400", summer), "2021-07-15 08:00 -04:00");
+        assert_eq!(format("-4", summer), "2021-07-15 08:00 -04:00");
+        assert_eq!(format("+05:30", summer), "2021-07-15 17:30 +05:30");
+    }
+
+    #[test]
+    fn malformed_timezones_are_rejected() {
+        assert!("America/Nowhere".parse::<DisplayTimezone>().is_err());
+        assert!("04:00".parse::<DisplayTimezone>().is_err());
+        assert!("-24:00".parse::<DisplayTimezone>().is_err());
+        assert!("-04:60".parse::<DisplayTimezone>().is_err());
+        assert!("-040".parse::<DisplayTimezone>().is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use image::imageops::FilterType;
+use image::{GenericImageView, ImageFormat, ImageOutputFormat};
+use std::str::FromStr;
+
+/// How much to shrink images before they are stored, written on the command line as a scale
+/// factor (`0.5`) or as target dimensions (`320x240`).
+#[derive(Clone, Copy, Debug)]
+pub enum Downscale {
+    Factor(f64),
+    /// A box the image is shrunk to fit within, keeping its aspect ratio. Images that already
+    /// fit are left at their original size rather than enlarged.
+    Dimensions(u32, u32),
+}
+
+impl FromStr for Downscale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(index) = s.find('x') {
+            let (width, height) = (&s[..index], &s[index + 1..]);
+            return match (width.parse::<u32>(), height.parse::<u32>()) {
+                (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok(Downscale::Dimensions(width, height)),
+                _ => Err(format!("Invalid downscale dimensions '{}'; expected e.g. 320x240.", s)),
+            };
+        }
+
+        match s.parse::<f64>() {
+            Ok(factor) if factor > 0.0 && factor <= 1.0 => Ok(Downscale::Factor(factor)),
+            _ => Err(format!("Invalid downscale factor '{}'; expected a number greater than 0 and at most 1.", s)),
+        }
+    }
+}
+
+impl Downscale {
+    /// Decodes a GIF, shrinks it and encodes it as a GIF again. Nearest neighbour sampling is
+    /// used so that every pixel keeps one of the original palette colours, which is what the
+    /// colour scales on radar images rely on.
+    pub fn apply(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        let image = image::load_from_memory_with_format(bytes, ImageFormat::Gif).map_err(|err| format!("Failed to decode image: {}", err))?;
+        let (width, height) = self.target_size(image.width(), image.height());
+
+        let mut scaled = Vec::new();
+        image.resize_exact(width, height, FilterType::Nearest)
+            .write_to(&mut scaled, ImageOutputFormat::Gif)
+            .map_err(|err| format!("Failed to encode downscaled image: {}", err))?;
+        Ok(scaled)
+    }
+
+    /// The size an image of the given size is shrunk to.
+    fn target_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let factor = match *self {
+            Downscale::Factor(factor) => factor,
+            Downscale::Dimensions(box_width, box_height) => (f64::from(box_width) / f64::from(width))
+                .min(f64::from(box_height) / f64::from(height))
+                .min(1.0),
+        };
+        (
+            ((f64::from(width) * factor).round() as u32).max(1),
+            ((f64::from(height) * factor).round() as u32).max(1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target_size(downscale: &str, width: u32, height: u32) -> (u32, u32) {
+        downscale.parse::<Downscale>().unwrap().target_size(width, height)
+    }
+
+    #[test]
+    fn factors_scale_both_sides() {
+        assert_eq!(target_size("0.5", 580, 480), (290, 240));
+        assert_eq!(target_size("0.001", 580, 480), (1, 1));
+    }
+
+    #[test]
+    fn dimensions_keep_the_aspect_ratio() {
+        assert_eq!(target_size("290x240", 580, 480), (290, 240));
+        assert_eq!(target_size("320x240", 580, 480), (290, 240));
+        assert_eq!(target_size("290x1000", 580, 480), (290, 240));
+    }
+
+    #[test]
+    fn dimensions_never_enlarge() {
+        assert_eq!(target_size("1000x1000", 580, 480), (580, 480));
+        assert_eq!(target_size("580x480", 580, 480), (580, 480));
+    }
+
+    #[test]
+    fn malformed_downscales_are_rejected() {
+        assert!("0".parse::<Downscale>().is_err());
+        assert!("1.5".parse::<Downscale>().is_err());
+        assert!("320x0".parse::<Downscale>().is_err());
+        assert!("320x".parse::<Downscale>().is_err());
+    }
+}
@@ -0,0 +1,2 @@
+#[cfg(feature = "mock-server")]
+pub mod mock_server;
@@ -8,23 +8,109 @@ extern crate slog_term;
 extern crate slog_async;
 extern crate ureq;
 
-use chrono::{Duration, TimeZone, Utc};
-use clap::{Arg, App};
+mod cadence;
+mod catalog;
+mod display;
+mod downscale;
+mod outages;
+mod registry;
+mod snapshot;
+mod transfer;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use clap::{Arg, App, AppSettings, ArgMatches, SubCommand};
 use indicatif::ProgressBar;
 use slog::Drain;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use rayon::prelude::*;
-use ureq::Error;
+use catalog::Catalog;
+use display::DisplayTimezone;
+use downscale::Downscale;
+use outages::Outages;
+use registry::Combination;
+use snapshot::Snapshot;
+use transfer::{MinimumSpeed, TransferError};
+use ureq::{Agent, AgentBuilder, Error};
 
 fn command_usage<'a, 'b>() -> App<'a, 'b> {
     const DEFAULT_START_HOUR: &str = "0";
+    const DEFAULT_STALL_RETRIES: &str = "3";
+    const DEFAULT_DISPLAY_TIMEZONE: &str = "UTC";
+    const DEFAULT_INTERVAL: &str = "auto";
+
+    let volume = Arg::with_name("volume")
+        .long("volume")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Another directory holding part of the same archive, e.g. a previous year's disk. Images found on any volume are not downloaded again. May be given more than once.");
+
+    let display_timezone = Arg::with_name("display-timezone")
+        .long("display-timezone")
+        .takes_value(true)
+        .default_value(DEFAULT_DISPLAY_TIMEZONE)
+        .validator(|value| value.parse::<DisplayTimezone>().map(|_| ()))
+        .help("Timezone to show timestamps in, as a timezone name (e.g. America/Halifax, following daylight saving time) or a fixed UTC offset (e.g. -04:00). Dates on the command line and in file names are always UTC.");
 
     App::new("data-acquisition")
     .author("Matthew Scheffel <matt@dataheck.com>")
     .about("Downloads historical weather radar images from Environment and Climate Change Canada")
+    .setting(AppSettings::SubcommandsNegateReqs)
+    .subcommand(
+        SubCommand::with_name("cadence")
+            .about("Reports how often images were published over time, as inferred from the archive")
+            .arg(
+                Arg::with_name("directory")
+                    .long("directory")
+                    .takes_value(true)
+                    .required(true)
+                    .help("The archive directory to inspect")
+            )
+            .arg(volume.clone())
+            .arg(
+                Arg::with_name("site")
+                    .short("s")
+                    .long("site")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Which site to report on. Every image type archived for the site is reported.")
+            )
+            .arg(display_timezone.clone())
+    )
+    .subcommand(
+        SubCommand::with_name("snapshot")
+            .about("Records or compares the exact contents of an archive")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("create")
+                    .about("Writes a manifest of every image in the archive along with a hash of the archive state")
+                    .arg(
+                        Arg::with_name("directory")
+                            .long("directory")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The archive directory to snapshot")
+                    )
+                    .arg(volume.clone())
+                    .arg(
+                        Arg::with_name("output")
+                            .long("output")
+                            .takes_value(true)
+                            .required(true)
+                            .help("Where to write the snapshot manifest")
+                    )
+            )
+            .subcommand(
+                SubCommand::with_name("diff")
+                    .about("Lists the images added, removed and changed between two snapshots")
+                    .arg(Arg::with_name("earlier").required(true).help("The earlier snapshot manifest"))
+                    .arg(Arg::with_name("later").required(true).help("The later snapshot manifest"))
+                    .arg(display_timezone.clone())
+            )
+    )
     .arg(
         Arg::with_name("site")
             .short("s")
@@ -38,7 +124,12 @@ fn command_usage<'a, 'b>() -> App<'a, 'b> {
             .long("image-type")
             .takes_value(true)
             .required(true)
-            .help("What kind of image type to request. Examples: PERCIP_SNOW_WEATHEROFFICE, PERCIP_RAIN_WEATHEROFFICE")
+            .help("What kind of image type to request. Examples: PRECIPET_SNOW_WEATHEROFFICE, PRECIPET_RAIN_WEATHEROFFICE")
+    )
+    .arg(
+        Arg::with_name("skip-registry-check")
+            .long("skip-registry-check")
+            .help("Request the image type even if the site is known not to publish it")
     )
     .arg(
         Arg::with_name("start-year")
@@ -89,6 +180,20 @@ fn command_usage<'a, 'b>() -> App<'a, 'b> {
             .default_value(DEFAULT_START_HOUR)
             .help("Collection will start with this hour")
     )
+    .arg(
+        Arg::with_name("interval")
+            .long("interval")
+            .takes_value(true)
+            .default_value(DEFAULT_INTERVAL)
+            .validator(|value| match value.as_str() {
+                "auto" => Ok(()),
+                minutes => match minutes.parse::<i64>() {
+                    Ok(minutes) if minutes > 0 => Ok(()),
+                    _ => Err(format!("Invalid interval '{}'; expected a number of minutes or 'auto'.", minutes)),
+                },
+            })
+            .help("Minutes between requested images. 'auto' uses the publication interval inferred from the contents of images already in the archive for each month, and hourly where the archive has none.")
+    )
     .arg(
         Arg::with_name("directory")
             .long("directory")
@@ -96,116 +201,450 @@ fn command_usage<'a, 'b>() -> App<'a, 'b> {
             .required(true)
             .help("Where the downloaded images should be stored. Directory will be created if it does not exist. If the directory does exist, the software will not download existing files.")
     )
+    .arg(volume)
+    .arg(
+        Arg::with_name("base-url")
+            .long("base-url")
+            .takes_value(true)
+            .default_value(IMAGE_BASE_URL)
+            .help("The image endpoint to request frames from. Only useful for pointing at a mirror or a mock server.")
+    )
+    .arg(
+        Arg::with_name("min-speed")
+            .long("min-speed")
+            .takes_value(true)
+            .validator(|value| value.parse::<MinimumSpeed>().map(|_| ()))
+            .help("Abort and retry a download whose speed stays below this rate for the given window, e.g. 10kB/s@30s")
+    )
+    .arg(
+        Arg::with_name("stall-retries")
+            .long("stall-retries")
+            .takes_value(true)
+            .default_value(DEFAULT_STALL_RETRIES)
+            .help("How many times a stalled download is retried before giving up on that image")
+    )
+    .arg(
+        Arg::with_name("downscale")
+            .long("downscale")
+            .takes_value(true)
+            .validator(|value| value.parse::<Downscale>().map(|_| ()))
+            .help("Shrink images before storing them, by a factor (e.g. 0.5) or to fit within a size (e.g. 320x240), keeping the aspect ratio")
+    )
+    .arg(
+        Arg::with_name("keep-original")
+            .long("keep-original")
+            .requires("downscale")
+            .help("When downscaling, also store the full size image in an 'original' subdirectory")
+    )
+    .arg(
+        Arg::with_name("outages")
+            .long("outages")
+            .takes_value(true)
+            .help("A CSV file of known radar outages (site,start,end[,reason], times in UTC). Images missing during an outage are reported separately from failed downloads.")
+    )
+    .arg(display_timezone)
 }
 
-fn process_file(file_url: &str, directory: &str, identifier: &str) -> Result<(), ()> {
+fn logger() -> slog::Logger {
     let decorator = slog_term::TermDecorator::new().build();
     let drain = slog_term::FullFormat::new(decorator).build().fuse();
     let drain = slog_async::Async::new(drain).build().fuse();
 
-    let log = slog::Logger::root(drain, o!());
+    slog::Logger::root(drain, o!())
+}
+
+/// Settings shared by every image download in a run.
+struct Fetch<'a> {
+    agent: Agent,
+    directory: &'a str,
+    minimum_speed: Option<MinimumSpeed>,
+    stall_retries: u32,
+    downscale: Option<Downscale>,
+    keep_original: bool,
+}
+
+/// How a request that got an answer from the server ended.
+enum Fetched {
+    Stored,
+    /// The server answered with an empty body, which is how it says it has no image at all.
+    NotPublished,
+}
+
+/// Why a requested image is missing from the archive.
+#[derive(Clone, Copy, PartialEq)]
+enum Gap {
+    NotPublished,
+    Substituted,
+    Failed,
+}
 
-    let file_processor = log.new(o!("file_url" => file_url.to_owned()));
+fn process_file(fetch: &Fetch, file_url: &str, identifier: &str, frame_time: &str) -> Result<Fetched, ()> {
+    let file_processor = logger().new(o!("file_url" => file_url.to_owned(), "frame_time" => frame_time.to_owned()));
 
-    match ureq::get(file_url).call() {
-        Ok(response) => {
-            if !Path::new(directory).exists() {
-                std::fs::create_dir(directory).expect("Failed to create specified directory, which does not exist.");
+    let mut attempt = 0;
+    let bytes = loop {
+        let transfer = match fetch.agent.get(file_url).call() {
+            Ok(response) => transfer::read_to_end(response.into_reader(), fetch.minimum_speed.as_ref()),
+            // The read timeout also bounds the wait for the response headers, so a server that is
+            // slow to start answering is retried the same way as a stalled body.
+            Err(ref err) if fetch.minimum_speed.is_some() && transfer::is_timeout(err) => Err(TransferError::Stalled),
+            Err(Error::Status(code, _)) => {
+                error!(file_processor, "HTTP error code {} recieved when fetching url.", code);
+                return Err(());
+            },
+            Err(_) => {
+                error!(file_processor, "I/O or transport error occured when fetching url.");
+                return Err(());
             }
+        };
 
-            let concat = format!("{directory}/{identifier}", directory=directory, identifier=identifier);
-            let path = Path::new(&concat);
-
-            let mut bytes = Vec::new();
-            response.into_reader().read_to_end(&mut bytes).expect("Failed to process response from server as an array of bytes.");
-            if !bytes.is_empty() {
-                let mut file = match File::create(path) {
-                    Ok(f) => { f },
-                    Err(err) => {
-                        error!(file_processor, "Failed to create file due to error: '{}'", err);
-                        return Err(());
-                    }
-                };
-                file.write_all(&bytes).expect("Failed to write bytes to file.");
-                Ok(())
-            } else {
-                Err(())
+        match transfer {
+            Ok(bytes) => break bytes,
+            Err(TransferError::Stalled) if attempt < fetch.stall_retries => {
+                attempt += 1;
+                warn!(file_processor, "Transfer stalled below the minimum speed, retrying (attempt {} of {}).", attempt, fetch.stall_retries);
+            },
+            Err(TransferError::Stalled) => {
+                error!(file_processor, "Transfer stalled below the minimum speed on every attempt, giving up.");
+                return Err(());
+            },
+            Err(TransferError::Io(err)) => {
+                error!(file_processor, "Failed to read response from server due to error: '{}'", err);
+                return Err(());
             }
-        },
-        Err(Error::Status(code, _)) => {
-            error!(file_processor, "HTTP error code {} recieved when fetching url.", code);
-            Err(())
-        },
-        Err(_) => {
-            error!(file_processor, "I/O or transport error occured when fetching url.");
-            Err(())
         }
+    };
+
+    if bytes.is_empty() {
+        return Ok(Fetched::NotPublished);
+    }
+
+    // The server answers with an HTML page instead of an image during maintenance. Storing it
+    // would leave a file that the catalog then treats as an archived frame.
+    if !bytes.starts_with(b"GIF8") {
+        error!(file_processor, "Response was not a GIF image, not storing it.");
+        return Err(());
     }
+
+    let directory = fetch.directory;
+    if !Path::new(directory).exists() {
+        std::fs::create_dir(directory).expect("Failed to create specified directory, which does not exist.");
+    }
+
+    let stored = match fetch.downscale {
+        Some(downscale) => {
+            let scaled = match downscale.apply(&bytes) {
+                Ok(scaled) => scaled,
+                Err(err) => {
+                    error!(file_processor, "{}", err);
+                    return Err(());
+                }
+            };
+            if fetch.keep_original {
+                let originals = format!("{directory}/original", directory=directory);
+                std::fs::create_dir_all(&originals).expect("Failed to create directory for original images.");
+                write_file(&file_processor, &format!("{originals}/{identifier}", originals=originals, identifier=identifier), &bytes)?;
+            }
+            scaled
+        },
+        None => bytes,
+    };
+
+    write_file(&file_processor, &format!("{directory}/{identifier}", directory=directory, identifier=identifier), &stored)
+        .map(|()| Fetched::Stored)
+}
+
+fn write_file(log: &slog::Logger, path: &str, bytes: &[u8]) -> Result<(), ()> {
+    let mut file = match File::create(Path::new(path)) {
+        Ok(f) => { f },
+        Err(err) => {
+            error!(log, "Failed to create file due to error: '{}'", err);
+            return Err(());
+        }
+    };
+    file.write_all(bytes).expect("Failed to write bytes to file.");
+    Ok(())
 }
 
 fn main() {
     let matches = command_usage().get_matches();
 
-    let start_date = Utc.ymd(
+    match matches.subcommand() {
+        ("cadence", Some(cadence_matches)) => cadence_report(cadence_matches),
+        ("snapshot", Some(snapshot_matches)) => match snapshot_matches.subcommand() {
+            ("create", Some(create_matches)) => snapshot_create(create_matches),
+            ("diff", Some(diff_matches)) => snapshot_diff(diff_matches),
+            _ => unreachable!("clap requires a snapshot subcommand"),
+        },
+        _ => download(&matches),
+    }
+}
+
+/// Catalogs the directory given with `--directory` followed by every `--volume`, in the order given.
+fn scan_archive(matches: &ArgMatches) -> Catalog {
+    let volumes: Vec<PathBuf> = std::iter::once(matches.value_of("directory").unwrap())
+        .chain(matches.values_of("volume").into_iter().flatten())
+        .map(PathBuf::from)
+        .collect();
+    Catalog::scan(&volumes).unwrap_or_else(|err| panic!("Failed to walk archive volumes to find existing files: {}", err))
+}
+
+fn cadence_report(matches: &ArgMatches) {
+    let site = matches.value_of("site").unwrap();
+    let display_timezone = matches.value_of("display-timezone").unwrap().parse::<DisplayTimezone>().unwrap();
+    let catalog = scan_archive(matches);
+
+    for (image_type, frames) in cadence::frame_paths(&catalog, site) {
+        let frames = cadence::hash_frames(&frames).expect("Failed to read archive files while inferring the publication cadence.");
+        println!("{}", image_type);
+        for era in cadence::infer(&frames) {
+            let interval = if era.resolved {
+                format!("every {} minutes", era.interval_minutes)
+            } else {
+                format!("every {} minutes or more often", era.interval_minutes)
+            };
+            println!(
+                "  {} to {}  {}  ({} images, {} files)",
+                display_timezone.format(era.first), display_timezone.format(era.last), interval, era.images, era.frames
+            );
+        }
+    }
+}
+
+fn snapshot_create(matches: &ArgMatches) {
+    let catalog = scan_archive(matches);
+    let snapshot = Snapshot::create(&catalog).expect("Failed to read archive files while creating snapshot.");
+    snapshot.write(Path::new(matches.value_of("output").unwrap())).expect("Failed to write snapshot manifest.");
+
+    println!("Snapshot of {} images, state {}", snapshot.frames.len(), snapshot.state());
+}
+
+fn snapshot_diff(matches: &ArgMatches) {
+    let read = |name: &str| Snapshot::read(Path::new(matches.value_of(name).unwrap())).unwrap_or_else(|err| panic!("{}", err));
+    let earlier = read("earlier");
+    let later = read("later");
+    let diff = earlier.diff(&later);
+    let display_timezone = matches.value_of("display-timezone").unwrap().parse::<DisplayTimezone>().unwrap();
+
+    println!("{} state {} ({})", matches.value_of("earlier").unwrap(), earlier.state(), display_timezone.format(earlier.created));
+    println!("{} state {} ({})", matches.value_of("later").unwrap(), later.state(), display_timezone.format(later.created));
+    for (label, marker, names) in &[("Added", '+', &diff.added), ("Removed", '-', &diff.removed), ("Changed", '~', &diff.changed)] {
+        println!("{} ({}):", label, names.len());
+        for name in names.iter() {
+            println!("  {} {}", marker, name);
+        }
+    }
+}
+
+fn download(matches: &ArgMatches) {
+    let site = matches.value_of("site").unwrap();
+    let image_type = matches.value_of("image-type").unwrap();
+
+    if !matches.is_present("skip-registry-check") {
+        let log = logger().new(o!("site" => site.to_owned(), "image_type" => image_type.to_owned()));
+        match registry::check(site, image_type) {
+            Combination::Valid => {},
+            Combination::UnknownSite => {
+                warn!(log, "Site is not in the registry, so the image type could not be checked.");
+            },
+            Combination::Impossible(products) => {
+                error!(log, "This site never publishes the requested image type. It publishes: {}", products.join(", "));
+                // Dropping the logger flushes the asynchronous drain before exiting.
+                drop(log);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let start_date = NaiveDate::from_ymd_opt(
         matches.value_of("start-year").unwrap().parse::<i32>().unwrap_or_else(|_| panic!("Invalid start-year specified.")),
         matches.value_of("start-month").unwrap().parse::<u32>().unwrap_or_else(|_| panic!("Invalid start-month specified.")), 
         matches.value_of("start-day").unwrap().parse::<u32>().unwrap_or_else(|_| panic!("Invalid start-day specified.")), 
-    );
+    ).unwrap_or_else(|| panic!("Invalid start date specified."));
 
-    let end_date = Utc.ymd(
+    let end_date = NaiveDate::from_ymd_opt(
         matches.value_of("end-year").unwrap().parse::<i32>().unwrap_or_else(|_| panic!("Invalid end-year specified.")),
         matches.value_of("end-month").unwrap().parse::<u32>().unwrap_or_else(|_| panic!("Invalid end-month specified.")), 
         matches.value_of("end-day").unwrap().parse::<u32>().unwrap_or_else(|_| panic!("Invalid end-day specified.")), 
-    );
+    ).unwrap_or_else(|| panic!("Invalid end date specified."));
 
     let directory = matches.value_of("directory").unwrap();
+    let base_url = matches.value_of("base-url").unwrap();
 
-    let existing_files = {
-        if !Path::new(directory).exists() {
-            std::fs::create_dir(directory).expect("Failed to create specified directory, which does not exist.");
-            None
-        } else {
-            Some(
-                std::fs::read_dir(directory).expect("Failed to read directory, though it exists.")
-                .map(|res| res.map(|e| e.file_name().to_str().unwrap().to_owned()))
-                .collect::<Result<Vec<_>, std::io::Error>>().expect("Failed to walk directory to find existing files.")
-            )
-        }
+    let minimum_speed = matches.value_of("min-speed").map(|value| value.parse::<MinimumSpeed>().unwrap());
+    let stall_retries = matches.value_of("stall-retries").unwrap().parse::<u32>().unwrap_or_else(|_| panic!("Invalid stall-retries specified."));
+    let display_timezone = matches.value_of("display-timezone").unwrap().parse::<DisplayTimezone>().unwrap();
+    let outages = matches.value_of("outages").map(|path| Outages::read(Path::new(path)).unwrap_or_else(|err| panic!("{}", err)));
+
+    let fetch = Fetch {
+        agent: match minimum_speed {
+            // A silent socket never returns from a read, so bound each read by the stall window.
+            Some(minimum) => AgentBuilder::new().timeout_read(minimum.window).build(),
+            None => AgentBuilder::new().build(),
+        },
+        directory,
+        minimum_speed,
+        stall_retries,
+        downscale: matches.value_of("downscale").map(|value| value.parse::<Downscale>().unwrap()),
+        keep_original: matches.is_present("keep-original"),
     };
+
+    if !Path::new(directory).exists() {
+        std::fs::create_dir(directory).expect("Failed to create specified directory, which does not exist.");
+    }
+
+    let catalog = scan_archive(matches);
     
+    let fixed_interval = match matches.value_of("interval").unwrap() {
+        "auto" => None,
+        minutes => Some(minutes.parse::<i64>().unwrap()),
+    };
+    // Only the requested months matter, and hashing the whole archive can take a while. Each
+    // file is hashed once, for both the cadence inference and the gaps report.
+    let months = (start_date.year(), start_date.month())..=(end_date.year(), end_date.month());
+    let frames: Vec<_> = cadence::frame_paths(&catalog, site).remove(image_type).unwrap_or_default().into_iter()
+        .filter(|(time, _)| months.contains(&(time.year(), time.month())))
+        .collect();
+    let hashes = cadence::hash_frames(&frames).expect("Failed to read archive files while inferring the publication cadence.");
+    let eras = cadence::infer(&hashes);
+    let hashes: HashMap<DateTime<Utc>, String> = hashes.into_iter().collect();
+
     let mut file_urls = Vec::new();
-    
-    let mut dt = start_date;
-    while dt <= end_date {
-        for hour in 0 .. 23 { 
-            let file_name = format!(
-                "{site}_{imagetype}_{year}-{month}-{day}T{hour}-00.gif",
-                year=dt.format("%Y"), month=dt.format("%m"), day=dt.format("%d"), hour=format!("{:02}", hour),
-                site=matches.value_of("site").unwrap(), imagetype=matches.value_of("image-type").unwrap()
-            );
-            
-            if let Some(file_list) = existing_files.as_ref() {
-                if file_list.iter().any(|x| x == &file_name) {
-                    continue;
-                }
-            }
+    let mut slots = Vec::new();
 
+    let mut frame_time = Utc.from_utc_datetime(&start_date.and_hms_opt(0, 0, 0).unwrap());
+    let end = Utc.from_utc_datetime(&(end_date + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap());
+    while frame_time < end {
+        let file_name = format!(
+            "{site}_{imagetype}_{time}.gif",
+            time=frame_time.format("%Y-%m-%dT%H-%M"), site=site, imagetype=image_type
+        );
+
+        if !catalog.contains(&file_name) {
             let fetch_url = format!(
-                "{base}?time={year}{month}{day}{hour}00&site={site}&image_type={imagetype}", 
-                base=IMAGE_BASE_URL, year=dt.format("%Y"), month=dt.format("%m"), day=dt.format("%d"),
-                hour=format!("{:02}", hour), site=matches.value_of("site").unwrap(), imagetype=matches.value_of("image-type").unwrap()
+                "{base}?time={time}&site={site}&image_type={imagetype}",
+                base=base_url, time=frame_time.format("%Y%m%d%H%M"), site=site, imagetype=image_type
             );
-            file_urls.push((fetch_url, file_name ));
+            file_urls.push((fetch_url, file_name, frame_time));
         }
-        dt = dt + Duration::days(1);
+        slots.push(frame_time);
+
+        let interval = fixed_interval.unwrap_or_else(|| cadence::planned_interval(&eras, frame_time, start_date));
+        frame_time += Duration::minutes(interval);
     }
 
     let bar = ProgressBar::new(file_urls.len() as u64);
 
-    let _results: Vec<Result<(), ()>> = file_urls.par_iter().map(
-        |(path, identifier)| 
-        { bar.inc(1); process_file(path, directory, identifier)}
+    let results: Vec<Result<Fetched, ()>> = file_urls.par_iter().map(
+        |(path, identifier, frame_time)| 
+        { bar.inc(1); process_file(&fetch, path, identifier, &display_timezone.format(*frame_time))}
     ).collect();
 
     bar.finish();
+
+    let mut gaps = Vec::new();
+    let mut downloaded = HashMap::new();
+    for ((_, file_name, frame_time), result) in file_urls.iter().zip(results.iter()) {
+        match result {
+            Ok(Fetched::Stored) => { downloaded.insert(*frame_time, file_name.as_str()); },
+            Ok(Fetched::NotPublished) => gaps.push((*frame_time, Gap::NotPublished)),
+            Err(()) => gaps.push((*frame_time, Gap::Failed)),
+        }
+    }
+    let in_outage = |time: DateTime<Utc>| outages.as_ref().is_some_and(|outages| outages.find(site, time).is_some());
+    for frame_time in substituted_frames(&slots, &hashes, directory, &downloaded, &eras, in_outage) {
+        gaps.push((frame_time, Gap::Substituted));
+    }
+    report_gaps(site, file_urls.len(), gaps, outages.as_ref(), &display_timezone);
+}
+
+/// Finds the frames downloaded in this run that stand in for an image that was never published.
+///
+/// The server answers with the nearest image it has, so a missing image shows up as a run of
+/// identical frames. A run is only suspicious when it spans more publications than one under
+/// the expected cadence; requesting more often than images were published repeats them anyway.
+/// Which frame of a run holds the real image cannot be told from its content, so the frames at
+/// the expected publication times are taken to be substitutes, keeping the first one outside a
+/// known outage as the real image. Frames already in the archive were hashed before planning;
+/// only the ones downloaded now are read.
+fn substituted_frames(
+    slots: &[DateTime<Utc>],
+    hashes: &HashMap<DateTime<Utc>, String>,
+    directory: &str,
+    downloaded: &HashMap<DateTime<Utc>, &str>,
+    eras: &[cadence::Era],
+    in_outage: impl Fn(DateTime<Utc>) -> bool,
+) -> Vec<DateTime<Utc>> {
+    let mut frames: Vec<(DateTime<Utc>, String)> = Vec::new();
+    for frame_time in slots {
+        let hash = match downloaded.get(frame_time) {
+            Some(file_name) => match snapshot::hash_file(&Path::new(directory).join(file_name)) {
+                Ok(entry) => entry.hash,
+                Err(_) => continue,
+            },
+            None => match hashes.get(frame_time) {
+                Some(hash) => hash.clone(),
+                None => continue,
+            },
+        };
+        frames.push((*frame_time, hash));
+    }
+
+    let mut substituted = Vec::new();
+    let mut run_start = 0;
+    while run_start < frames.len() {
+        let run_end = frames[run_start..].iter()
+            .position(|(_, hash)| *hash != frames[run_start].1)
+            .map_or(frames.len(), |offset| run_start + offset);
+        let run: Vec<DateTime<Utc>> = frames[run_start..run_end].iter().map(|(time, _)| *time).collect();
+        run_start = run_end;
+
+        // Each publication answers the requests within about one interval of it, so a run covers
+        // roughly one interval per publication.
+        let first = run[0];
+        let interval = cadence::expected_interval(eras, first);
+        let step = run.windows(2).map(|pair| (pair[1] - pair[0]).num_minutes()).min().unwrap_or(0);
+        let covered = (run[run.len() - 1] - first).num_minutes() + step;
+        let publications = ((covered + interval / 2) / interval).max(1);
+        let mut expected: Vec<DateTime<Utc>> = (0..publications)
+            .filter_map(|index| {
+                let target = first + Duration::minutes(index * interval);
+                run.iter().copied().min_by_key(|time| (*time - target).num_minutes().abs())
+            })
+            .collect();
+        expected.dedup();
+
+        let real = expected.iter().position(|time| !in_outage(*time)).unwrap_or(0);
+        substituted.extend(expected.into_iter().enumerate()
+            .filter(|(index, time)| *index != real && downloaded.contains_key(time))
+            .map(|(_, time)| time));
+    }
+
+    substituted
+}
+
+/// Summarises the images that are missing from the archive, separating those missing because
+/// the radar was down from those the server never had, substituted or failed to download.
+fn report_gaps(site: &str, requested: usize, mut gaps: Vec<(DateTime<Utc>, Gap)>, outages: Option<&Outages>, display_timezone: &DisplayTimezone) {
+    gaps.sort_by_key(|(time, _)| *time);
+
+    let explained: Vec<(DateTime<Utc>, Gap, Option<&str>)> = gaps.into_iter()
+        .map(|(time, gap)| (time, gap, outages.and_then(|outages| outages.find(site, time)).map(|outage| outage.reason.as_str())))
+        .collect();
+    let count = |kind: Gap| explained.iter().filter(|(_, gap, outage)| *gap == kind && outage.is_none()).count();
+
+    println!(
+        "Gaps: {} requested, {} downloaded, {} missing during known outages, {} not published, {} substituted by a neighbouring image, {} failed",
+        requested, requested - explained.len(), explained.iter().filter(|(_, _, outage)| outage.is_some()).count(),
+        count(Gap::NotPublished), count(Gap::Substituted), count(Gap::Failed)
+    );
+    for (time, gap, outage) in explained {
+        match (outage, gap) {
+            (Some(""), _) => println!("  {}  radar outage", display_timezone.format(time)),
+            (Some(reason), _) => println!("  {}  radar outage: {}", display_timezone.format(time), reason),
+            (None, Gap::NotPublished) => println!("  {}  not published", display_timezone.format(time)),
+            (None, Gap::Substituted) => println!("  {}  substituted by a neighbouring image", display_timezone.format(time)),
+            (None, Gap::Failed) => println!("  {}  download failed", display_timezone.format(time)),
+        }
+    }
 }
@@ -0,0 +1,185 @@
+//! A local stand-in for the ECCC `image_e.html` endpoint.
+//!
+//! The real server answers every request with the frame nearest to the requested time, and
+//! is known to reply with empty bodies, `429 Too Many Requests` and HTML maintenance pages.
+//! `MockServer` reproduces those behaviours on a loopback port so that the downloader (or a
+//! crate integrating with it) can be exercised end to end without touching the network.
+
+use chrono::NaiveDateTime;
+use std::collections::{BTreeMap, HashMap};
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const IMAGE_PATH: &str = "/radar/image_e.html";
+const STALL_DURATION: Duration = Duration::from_secs(60);
+const MAINTENANCE_PAGE: &str = "<html><head><title>Maintenance</title></head><body>This service is temporarily unavailable due to scheduled maintenance.</body></html>";
+
+/// How the mock server answers a request that resolves to a given frame.
+#[derive(Clone, Debug)]
+pub enum Behaviour {
+    /// `200 OK` with the given bytes as an `image/gif` body.
+    Image(Vec<u8>),
+    /// `200 OK` with an empty body, as the real server does for frames it does not have.
+    Empty,
+    /// `429 Too Many Requests`.
+    TooManyRequests,
+    /// `200 OK` with an HTML maintenance notice in place of the image.
+    Maintenance,
+    /// Sends the response headers and then goes silent, as a stuck connection does.
+    Stall,
+    /// Accepts the request but never starts answering, as an overloaded server does.
+    Silent,
+}
+
+type Frames = HashMap<(String, String), BTreeMap<NaiveDateTime, Behaviour>>;
+
+pub struct MockServer {
+    address: SocketAddr,
+    frames: Arc<Mutex<Frames>>,
+    requests: Arc<Mutex<Vec<String>>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Binds to an ephemeral loopback port and starts serving in a background thread.
+    /// The server is stopped when the returned value is dropped.
+    pub fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind mock server to a loopback port.");
+        let address = listener.local_addr().expect("Failed to determine mock server address.");
+
+        let frames = Arc::new(Mutex::new(Frames::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let frames = Arc::clone(&frames);
+            let requests = Arc::clone(&requests);
+            let shutdown = Arc::clone(&shutdown);
+
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let frames = Arc::clone(&frames);
+                        let requests = Arc::clone(&requests);
+                        thread::spawn(move || handle_connection(stream, &frames, &requests));
+                    }
+                }
+            })
+        };
+
+        MockServer { address, frames, requests, shutdown, handle: Some(handle) }
+    }
+
+    /// The URL to use in place of the real `image_e.html` endpoint.
+    pub fn base_url(&self) -> String {
+        format!("http://{address}{path}", address=self.address, path=IMAGE_PATH)
+    }
+
+    /// Registers a frame for a site and image type. Requests for that site and image type are
+    /// answered with the behaviour of the registered frame nearest to the requested time.
+    pub fn add_frame(&self, site: &str, image_type: &str, time: NaiveDateTime, behaviour: Behaviour) {
+        self.frames.lock().unwrap()
+            .entry((site.to_owned(), image_type.to_owned()))
+            .or_default()
+            .insert(time, behaviour);
+    }
+
+    /// The request targets (path and query) received so far, in arrival order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake the accept loop so that it notices the shutdown flag.
+        let _ = TcpStream::connect(self.address);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, frames: &Mutex<Frames>, requests: &Mutex<Vec<String>>) {
+    let target = {
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).is_err() {
+            return;
+        }
+        // Drain the headers; none of them affect the response.
+        let mut header = String::new();
+        while let Ok(read) = reader.read_line(&mut header) {
+            if read == 0 || header == "\r\n" || header == "\n" {
+                break;
+            }
+            header.clear();
+        }
+        match request_line.split_whitespace().nth(1) {
+            Some(target) => target.to_owned(),
+            None => return,
+        }
+    };
+
+    requests.lock().unwrap().push(target.clone());
+
+    let behaviour = if target.starts_with(IMAGE_PATH) {
+        resolve(&target, frames)
+    } else {
+        None
+    };
+
+    let (status, content_type, body) = match behaviour {
+        Some(Behaviour::Image(bytes)) => ("200 OK", "image/gif", bytes),
+        Some(Behaviour::Empty) | None => ("200 OK", "image/gif", Vec::new()),
+        Some(Behaviour::TooManyRequests) => ("429 Too Many Requests", "text/plain", b"Too Many Requests".to_vec()),
+        Some(Behaviour::Maintenance) => ("200 OK", "text/html", MAINTENANCE_PAGE.as_bytes().to_vec()),
+        Some(Behaviour::Silent) => {
+            thread::sleep(STALL_DURATION);
+            return;
+        },
+        Some(Behaviour::Stall) => {
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: image/gif\r\nContent-Length: 1024\r\nConnection: close\r\n\r\n";
+            let _ = stream.write_all(head.as_bytes()).and_then(|_| stream.flush());
+            thread::sleep(STALL_DURATION);
+            return;
+        },
+    };
+
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {length}\r\nConnection: close\r\n\r\n",
+        status=status, content_type=content_type, length=body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(&body));
+}
+
+/// Finds the behaviour of the frame nearest to the requested time, mirroring how the real
+/// server substitutes the closest available image.
+fn resolve(target: &str, frames: &Mutex<Frames>) -> Option<Behaviour> {
+    let (_, query) = target.split_once('?')?;
+    let parameter = |name: &str| {
+        query.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_owned())
+    };
+
+    let time = NaiveDateTime::parse_from_str(&parameter("time")?, "%Y%m%d%H%M").ok()?;
+    let key = (parameter("site")?, parameter("image_type")?);
+
+    let frames = frames.lock().unwrap();
+    frames.get(&key)?
+        .iter()
+        .min_by_key(|(frame_time, _)| (**frame_time - time).num_seconds().abs())
+        .map(|(_, behaviour)| behaviour.clone())
+}
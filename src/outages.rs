@@ -0,0 +1,104 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::path::Path;
+
+/// A period during which a radar was known to be down, e.g. for scheduled maintenance.
+pub struct Outage {
+    pub site: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub reason: String,
+}
+
+pub struct Outages(Vec<Outage>);
+
+impl Outages {
+    /// Reads outages from a CSV file of `site,start,end[,reason]` lines, with times in UTC as
+    /// RFC 3339 or `YYYY-MM-DDTHH:MM`. Both ends are inclusive. A site of `*` applies to every
+    /// site. Blank lines, `#` comments and a `site,...` header line are ignored.
+    pub fn read(path: &Path) -> Result<Outages, String> {
+        let contents = std::fs::read_to_string(path).map_err(|err| format!("Failed to read outages file '{}': {}", path.display(), err))?;
+        parse(&contents, &path.display().to_string())
+    }
+
+    /// The outage, if any, covering a site at the given time.
+    pub fn find(&self, site: &str, time: DateTime<Utc>) -> Option<&Outage> {
+        self.0.iter().find(|outage| (outage.site == site || outage.site == "*") && outage.start <= time && time <= outage.end)
+    }
+}
+
+fn parse(contents: &str, source: &str) -> Result<Outages, String> {
+    let mut outages = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("site,") {
+            continue;
+        }
+
+        let invalid = || format!("Invalid outage on line {} of '{}': '{}'", number + 1, source, line);
+        let fields: Vec<&str> = line.splitn(4, ',').map(str::trim).collect();
+        if fields.len() < 3 {
+            return Err(invalid());
+        }
+
+        let start = parse_time(fields[1]).ok_or_else(invalid)?;
+        let end = parse_time(fields[2]).ok_or_else(invalid)?;
+        if end < start {
+            return Err(format!("Outage on line {} of '{}' ends before it starts: '{}'", number + 1, source, line));
+        }
+
+        outages.push(Outage {
+            site: fields[0].to_owned(),
+            start,
+            end,
+            reason: fields.get(3).map(|reason| reason.to_string()).unwrap_or_default(),
+        });
+    }
+
+    Ok(Outages(outages))
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|time| DateTime::from_naive_utc_and_offset(time, Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn outages_are_parsed() {
+        let outages = parse("site,start,end,reason\n\
+                             # scheduled\n\
+                             \n\
+                             CASBV,2021-01-01T03:00,2021-01-01 05:00,upgrade, phase 2\n\
+                             *,2021-02-01T00:00:00Z,2021-02-01T00:00:00Z\n", "test").unwrap();
+
+        let upgrade = outages.find("CASBV", Utc.with_ymd_and_hms(2021, 1, 1, 5, 0, 0).unwrap()).unwrap();
+        assert_eq!(upgrade.start, Utc.with_ymd_and_hms(2021, 1, 1, 3, 0, 0).unwrap());
+        assert_eq!(upgrade.reason, "upgrade, phase 2");
+        assert!(outages.find("CASBV", Utc.with_ymd_and_hms(2021, 1, 1, 5, 10, 0).unwrap()).is_none());
+        assert!(outages.find("CASFT", Utc.with_ymd_and_hms(2021, 1, 1, 4, 0, 0).unwrap()).is_none());
+
+        let everywhere = outages.find("CASFT", Utc.with_ymd_and_hms(2021, 2, 1, 0, 0, 0).unwrap()).unwrap();
+        assert_eq!(everywhere.reason, "");
+    }
+
+    #[test]
+    fn outages_ending_before_they_start_are_rejected() {
+        let err = parse("CASBV,2021-01-01T03:00,2021-01-01T02:00\n", "test").err().unwrap();
+        assert!(err.contains("line 1"), "{}", err);
+    }
+
+    #[test]
+    fn malformed_outages_are_rejected() {
+        assert!(parse("CASBV,2021-01-01T03:00\n", "test").is_err());
+        assert!(parse("CASBV,yesterday,2021-01-01T03:00\n", "test").is_err());
+    }
+}
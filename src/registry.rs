@@ -0,0 +1,83 @@
+//! Which image types each site publishes, used to refuse requests that can only return empty
+//! responses.
+//!
+//! ECCC does not publish this as a list. The table was collected by hand from the historical
+//! radar viewer at https://climate.weather.gc.ca/radar/index_e.html: its site list (mirrored in
+//! the `--site` help) and the image types it offers for a single radar and for an aggregation.
+//! It may be incomplete. When a site is found to publish another product, add it here; until
+//! then `--skip-registry-check` requests it regardless.
+
+/// Products published for individual radar sites.
+const SITE_PRODUCTS: &[&str] = &[
+    "PRECIPET_RAIN_WEATHEROFFICE",
+    "PRECIPET_SNOW_WEATHEROFFICE",
+    "COMP_PRECIPET_RAIN_WEATHEROFFICE",
+    "COMP_PRECIPET_SNOW_WEATHEROFFICE",
+];
+
+/// Products published for the national and regional aggregations. Composites are already
+/// what an aggregation is, so they have no `COMP_` variants.
+const AGGREGATE_PRODUCTS: &[&str] = &[
+    "PRECIPET_RAIN_WEATHEROFFICE",
+    "PRECIPET_SNOW_WEATHEROFFICE",
+];
+
+const SITES: &[(&str, &[&str])] = &[
+    ("CASBI", SITE_PRODUCTS),
+    ("CASCM", SITE_PRODUCTS),
+    ("CASFT", SITE_PRODUCTS),
+    ("CASGO", SITE_PRODUCTS),
+    ("CASKR", SITE_PRODUCTS),
+    ("CASLC", SITE_PRODUCTS),
+    ("CASLA", SITE_PRODUCTS),
+    ("CASBV", SITE_PRODUCTS),
+    ("CASVD", SITE_PRODUCTS),
+    ("CASSF", SITE_PRODUCTS),
+    ("NAT", AGGREGATE_PRODUCTS),
+    ("PYR", AGGREGATE_PRODUCTS),
+    ("PNR", AGGREGATE_PRODUCTS),
+    ("ONT", AGGREGATE_PRODUCTS),
+    ("QUE", AGGREGATE_PRODUCTS),
+    ("ATL", AGGREGATE_PRODUCTS),
+];
+
+pub enum Combination {
+    Valid,
+    /// The site is not in the registry, so nothing can be said about the product.
+    UnknownSite,
+    /// The site is known but never publishes the product; carries the products it does publish.
+    Impossible(&'static [&'static str]),
+}
+
+pub fn check(site: &str, image_type: &str) -> Combination {
+    match SITES.iter().find(|(known, _)| *known == site) {
+        Some((_, products)) if products.contains(&image_type) => Combination::Valid,
+        Some((_, products)) => Combination::Impossible(products),
+        None => Combination::UnknownSite,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn published_combinations_are_valid() {
+        assert!(matches!(check("CASBV", "PRECIPET_SNOW_WEATHEROFFICE"), Combination::Valid));
+        assert!(matches!(check("CASBV", "COMP_PRECIPET_RAIN_WEATHEROFFICE"), Combination::Valid));
+        assert!(matches!(check("ATL", "PRECIPET_RAIN_WEATHEROFFICE"), Combination::Valid));
+    }
+
+    #[test]
+    fn unpublished_combinations_list_the_published_products() {
+        match check("ATL", "COMP_PRECIPET_RAIN_WEATHEROFFICE") {
+            Combination::Impossible(products) => assert_eq!(products, AGGREGATE_PRODUCTS),
+            _ => panic!("Expected an impossible combination."),
+        }
+    }
+
+    #[test]
+    fn unknown_sites_are_not_judged() {
+        assert!(matches!(check("CASXX", "PRECIPET_RAIN_WEATHEROFFICE"), Combination::UnknownSite));
+    }
+}
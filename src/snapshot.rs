@@ -0,0 +1,165 @@
+//! Manifests recording the exact contents of an archive at a point in time, so that an
+//! analysis can name the archive state it used and later be compared against it.
+//!
+//! A manifest is a text file of `#`-prefixed header lines followed by one tab separated
+//! `name, size, SHA-256` line per frame, sorted by name. The state hash is the SHA-256 of the
+//! frame lines only, so two snapshots of identical archives share a state hash whenever they
+//! were taken and however the frames are spread over volumes.
+
+use crate::catalog::{self, Catalog};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+const HEADER: &str = "# canadian-historical-weather-radar snapshot";
+
+#[derive(Clone, PartialEq)]
+pub struct Entry {
+    pub size: u64,
+    pub hash: String,
+}
+
+pub struct Snapshot {
+    pub created: DateTime<Utc>,
+    pub frames: BTreeMap<String, Entry>,
+}
+
+pub struct Diff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+/// The size and hex encoded SHA-256 of a file's contents.
+pub fn hash_file(path: &Path) -> io::Result<Entry> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        hasher.update(buffer);
+        let read = buffer.len();
+        size += read as u64;
+        reader.consume(read);
+    }
+    Ok(Entry { size, hash: hex(&hasher.finalize()) })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl Snapshot {
+    /// Hashes the contents of every frame in the catalog. Other files, such as outage lists or
+    /// earlier manifests kept alongside the frames, are left out.
+    pub fn create(catalog: &Catalog) -> io::Result<Snapshot> {
+        let mut frames = BTreeMap::new();
+
+        for (name, path) in catalog.frames() {
+            if catalog::parse_frame_name(name).is_none() {
+                continue;
+            }
+            if name.contains(['\t', '\n']) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Frame name '{}' contains a tab or line break, which a manifest cannot record.", name.escape_debug())));
+            }
+            frames.insert(name.to_owned(), hash_file(path)?);
+        }
+
+        Ok(Snapshot { created: Utc::now(), frames })
+    }
+
+    /// A hash identifying the archive contents recorded by this snapshot.
+    pub fn state(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (name, entry) in &self.frames {
+            hasher.update(frame_line(name, entry).as_bytes());
+        }
+        hex(&hasher.finalize())
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+        writeln!(writer, "# state {}", self.state())?;
+        writeln!(writer, "# created {}", self.created.to_rfc3339())?;
+        for (name, entry) in &self.frames {
+            writer.write_all(frame_line(name, entry).as_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// Reads a manifest, refusing it unless the recorded state hash matches the frames listed,
+    /// so that a corrupted or edited manifest cannot stand in for the archive it claims to be.
+    pub fn read(path: &Path) -> Result<Snapshot, String> {
+        let reader = BufReader::new(File::open(path).map_err(|err| format!("Failed to open snapshot '{}': {}", path.display(), err))?);
+        let invalid = |line: &str| format!("Invalid line in snapshot '{}': '{}'", path.display(), line);
+
+        let mut lines = reader.lines();
+        match lines.next() {
+            Some(Ok(ref line)) if line == HEADER => {},
+            _ => return Err(format!("'{}' is not a snapshot manifest.", path.display())),
+        }
+
+        let mut state = None;
+        let mut created = None;
+        let mut frames = BTreeMap::new();
+
+        for line in lines {
+            let line = line.map_err(|err| format!("Failed to read snapshot '{}': {}", path.display(), err))?;
+            if let Some(header) = line.strip_prefix('#') {
+                let header = header.trim();
+                if let Some(timestamp) = header.strip_prefix("created ") {
+                    created = Some(DateTime::parse_from_rfc3339(timestamp).map_err(|_| invalid(&line))?.with_timezone(&Utc));
+                } else if let Some(hash) = header.strip_prefix("state ") {
+                    state = Some(hash.to_owned());
+                }
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 3 {
+                return Err(invalid(&line));
+            }
+            let size = fields[1].parse::<u64>().map_err(|_| invalid(&line))?;
+            frames.insert(fields[0].to_owned(), Entry { size, hash: fields[2].to_owned() });
+        }
+
+        let created = created.ok_or_else(|| format!("Snapshot '{}' has no creation time.", path.display()))?;
+        let state = state.ok_or_else(|| format!("Snapshot '{}' has no state hash.", path.display()))?;
+        let snapshot = Snapshot { created, frames };
+        if snapshot.state() != state {
+            return Err(format!("Snapshot '{}' does not match its state hash; it has been modified or corrupted.", path.display()));
+        }
+        Ok(snapshot)
+    }
+
+    /// The frames added, removed and changed going from `self` to `later`.
+    pub fn diff(&self, later: &Snapshot) -> Diff {
+        let mut diff = Diff { added: Vec::new(), removed: Vec::new(), changed: Vec::new() };
+
+        for (name, entry) in &self.frames {
+            match later.frames.get(name) {
+                None => diff.removed.push(name.clone()),
+                Some(other) if other != entry => diff.changed.push(name.clone()),
+                Some(_) => {},
+            }
+        }
+        diff.added = later.frames.keys()
+            .filter(|name| !self.frames.contains_key(*name))
+            .cloned()
+            .collect();
+
+        diff
+    }
+}
+
+fn frame_line(name: &str, entry: &Entry) -> String {
+    format!("{}\t{}\t{}\n", name, entry.size, entry.hash)
+}
@@ -0,0 +1,140 @@
+use std::io::{ErrorKind, Read};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// The slowest a transfer may be sustained over a window before it is considered stalled,
+/// written on the command line as e.g. `10kB/s@30s`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MinimumSpeed {
+    pub bytes_per_second: u64,
+    pub window: Duration,
+}
+
+impl FromStr for MinimumSpeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '@');
+        let rate = parts.next().unwrap_or_default();
+        let window = parts.next().ok_or_else(|| format!("Minimum speed '{}' is missing a window, e.g. '10kB/s@30s'.", s))?;
+
+        let rate = rate.strip_suffix("/s").ok_or_else(|| format!("Minimum speed rate '{}' must be given per second, e.g. '10kB/s'.", rate))?;
+        let (amount, unit) = split_unit(rate);
+        let multiplier = match unit {
+            "B" => 1,
+            "kB" | "KB" => 1_000,
+            "MB" => 1_000_000,
+            _ => return Err(format!("Unknown minimum speed unit '{}'; expected B, kB or MB.", unit)),
+        };
+        let amount = amount.parse::<u64>().map_err(|_| format!("Invalid minimum speed rate '{}'.", rate))?;
+
+        let (length, unit) = split_unit(window);
+        let multiplier_seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            _ => return Err(format!("Unknown minimum speed window unit '{}'; expected s or m.", unit)),
+        };
+        let length = length.parse::<u64>().map_err(|_| format!("Invalid minimum speed window '{}'.", window))?;
+        if length == 0 {
+            return Err("Minimum speed window must be longer than zero.".to_owned());
+        }
+
+        Ok(MinimumSpeed {
+            bytes_per_second: amount.checked_mul(multiplier).ok_or_else(|| format!("Minimum speed rate '{}' is too large.", rate))?,
+            window: Duration::from_secs(length.checked_mul(multiplier_seconds).ok_or_else(|| format!("Minimum speed window '{}' is too long.", window))?),
+        })
+    }
+}
+
+fn split_unit(s: &str) -> (&str, &str) {
+    let boundary = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(boundary)
+}
+
+/// Whether a request failed because the connection went silent for longer than the read
+/// timeout, such as while waiting for the response headers.
+pub fn is_timeout(err: &ureq::Error) -> bool {
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            return io.kind() == ErrorKind::TimedOut || io.kind() == ErrorKind::WouldBlock;
+        }
+        source = cause.source();
+    }
+    false
+}
+
+pub enum TransferError {
+    /// Throughput dropped below the minimum speed, or the connection went silent for a full window.
+    Stalled,
+    Io(std::io::Error),
+}
+
+/// Reads a response body to the end, giving up with `TransferError::Stalled` as soon as a full
+/// window passes with less data than the minimum speed demands. The underlying socket must have
+/// a read timeout no longer than the window, or a silent connection will never be noticed.
+pub fn read_to_end(mut reader: impl Read, minimum: Option<&MinimumSpeed>) -> Result<Vec<u8>, TransferError> {
+    let mut bytes = Vec::new();
+    let mut buffer = [0; 8192];
+    let mut window_start = Instant::now();
+    let mut window_bytes = 0;
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(bytes),
+            Ok(read) => {
+                bytes.extend_from_slice(&buffer[..read]);
+                window_bytes += read as u64;
+            },
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) if minimum.is_some() && (err.kind() == ErrorKind::TimedOut || err.kind() == ErrorKind::WouldBlock) => {
+                return Err(TransferError::Stalled);
+            },
+            Err(err) => return Err(TransferError::Io(err)),
+        }
+
+        if let Some(minimum) = minimum {
+            let elapsed = window_start.elapsed();
+            if elapsed >= minimum.window {
+                if window_bytes < minimum.bytes_per_second.saturating_mul(elapsed.as_secs()) {
+                    return Err(TransferError::Stalled);
+                }
+                window_start = Instant::now();
+                window_bytes = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimum_speed_units() {
+        assert_eq!("10kB/s@30s".parse(), Ok(MinimumSpeed { bytes_per_second: 10_000, window: Duration::from_secs(30) }));
+        assert_eq!("10KB/s@30s".parse(), Ok(MinimumSpeed { bytes_per_second: 10_000, window: Duration::from_secs(30) }));
+        assert_eq!("500B/s@2m".parse(), Ok(MinimumSpeed { bytes_per_second: 500, window: Duration::from_secs(120) }));
+        assert_eq!("1MB/s@5s".parse(), Ok(MinimumSpeed { bytes_per_second: 1_000_000, window: Duration::from_secs(5) }));
+    }
+
+    #[test]
+    fn minimum_speed_rejects_malformed_values() {
+        assert!("10kB/s".parse::<MinimumSpeed>().is_err());
+        assert!("10kB@30s".parse::<MinimumSpeed>().is_err());
+        assert!("10GB/s@30s".parse::<MinimumSpeed>().is_err());
+        assert!("10kB/s@30h".parse::<MinimumSpeed>().is_err());
+        assert!("kB/s@30s".parse::<MinimumSpeed>().is_err());
+    }
+
+    #[test]
+    fn minimum_speed_rejects_zero_window() {
+        assert!("10kB/s@0s".parse::<MinimumSpeed>().is_err());
+    }
+
+    #[test]
+    fn minimum_speed_rejects_overflowing_values() {
+        assert!("99999999999999999MB/s@30s".parse::<MinimumSpeed>().is_err());
+        assert!("10kB/s@999999999999999999m".parse::<MinimumSpeed>().is_err());
+    }
+}
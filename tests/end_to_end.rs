@@ -0,0 +1,468 @@
+use canadian_historical_weather_radar::mock_server::{Behaviour, MockServer};
+use chrono::{NaiveDate, NaiveDateTime};
+use image::GenericImageView;
+use std::ffi::OsStr;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const SITE: &str = "ATL";
+const IMAGE_TYPE: &str = "PRECIPET_RAIN_WEATHEROFFICE";
+const GIF_BYTES: &[u8] = b"GIF89a\x01\x00\x01\x00\x00\x00\x00;";
+
+fn frame_time(hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2021, 1, 1).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+}
+
+/// A directory under the system temporary directory, removed again when dropped.
+struct ScratchDirectory(PathBuf);
+
+impl Deref for ScratchDirectory {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for ScratchDirectory {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for ScratchDirectory {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
+
+impl Drop for ScratchDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn scratch_directory(name: &str) -> ScratchDirectory {
+    let path = std::env::temp_dir().join(format!("chwr-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    ScratchDirectory(path)
+}
+
+fn binary() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_canadian-historical-weather-radar"))
+}
+
+fn run(server: &MockServer, directory: &Path, extra: &[&str]) -> Output {
+    let output = run_for(server, directory, IMAGE_TYPE, extra);
+    assert!(output.status.success());
+    output
+}
+
+fn run_for(server: &MockServer, directory: &Path, image_type: &str, extra: &[&str]) -> Output {
+    binary()
+        .args(["--site", SITE, "--image-type", image_type])
+        .args(["--start-year", "2021", "--start-month", "1", "--start-day", "1"])
+        .args(["--end-year", "2021", "--end-month", "1", "--end-day", "1"])
+        .arg("--directory").arg(directory)
+        .arg("--base-url").arg(server.base_url())
+        .args(extra)
+        .output()
+        .expect("Failed to run downloader.")
+}
+
+fn stored_files(directory: &Path) -> Vec<String> {
+    let mut files: Vec<String> = fs::read_dir(directory).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_str().unwrap().to_owned())
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn nearest_frames_are_stored_for_every_requested_hour() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Image(GIF_BYTES.to_vec()));
+    let directory = scratch_directory("nearest");
+
+    run(&server, &directory, &[]);
+
+    let files = stored_files(&directory);
+    assert_eq!(files.len(), 24);
+    assert_eq!(files.len(), server.requests().len());
+    assert_eq!(files[0], "ATL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T00-00.gif");
+    assert_eq!(fs::read(directory.join(&files[0])).unwrap(), GIF_BYTES);
+}
+
+#[test]
+fn empty_bodies_are_not_stored() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Empty);
+    let directory = scratch_directory("empty");
+
+    run(&server, &directory, &[]);
+
+    assert!(!server.requests().is_empty());
+    assert!(stored_files(&directory).is_empty());
+}
+
+#[test]
+fn rate_limited_frames_are_not_stored() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::TooManyRequests);
+    let directory = scratch_directory("rate-limited");
+
+    run(&server, &directory, &[]);
+
+    assert!(!server.requests().is_empty());
+    assert!(stored_files(&directory).is_empty());
+}
+
+#[test]
+fn maintenance_pages_are_not_stored() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Maintenance);
+    let directory = scratch_directory("maintenance");
+
+    run(&server, &directory, &[]);
+
+    assert!(!server.requests().is_empty());
+    assert!(stored_files(&directory).is_empty());
+}
+
+#[test]
+fn existing_frames_are_not_requested_again() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Image(GIF_BYTES.to_vec()));
+    let directory = scratch_directory("existing");
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("ATL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T05-00.gif"), GIF_BYTES).unwrap();
+
+    run(&server, &directory, &[]);
+
+    assert!(!server.requests().is_empty());
+    assert!(server.requests().iter().all(|request| !request.contains("time=202101010500")));
+}
+
+#[test]
+fn downscaled_frames_are_stored_alongside_their_originals() {
+    let mut original = Vec::new();
+    image::DynamicImage::new_rgba8(8, 6)
+        .write_to(&mut original, image::ImageOutputFormat::Gif)
+        .unwrap();
+
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Image(original.clone()));
+    let directory = scratch_directory("downscale");
+
+    run(&server, &directory, &["--downscale", "0.5", "--keep-original"]);
+
+    let name = "ATL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T00-00.gif";
+    let scaled = image::open(directory.join(name)).unwrap();
+    assert_eq!((scaled.width(), scaled.height()), (4, 3));
+    assert_eq!(fs::read(directory.join("original").join(name)).unwrap(), original);
+}
+
+#[test]
+fn gaps_during_known_outages_are_reported_separately() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Empty);
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(12), Behaviour::TooManyRequests);
+    let directory = scratch_directory("outages-archive");
+    fs::create_dir_all(&directory).unwrap();
+    let outages = directory.join("outages.csv");
+    fs::write(&outages, "site,start,end,reason\nATL,2021-01-01T00:00,2021-01-01T05:00,scheduled maintenance\n").unwrap();
+
+    let output = run(&server, &directory, &["--outages", outages.to_str().unwrap()]);
+
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains(
+        "24 requested, 0 downloaded, 6 missing during known outages, 1 not published, 0 substituted by a neighbouring image, 17 failed"
+    ));
+    assert!(report.contains("2021-01-01 05:00 +00:00  radar outage: scheduled maintenance"));
+    assert!(report.contains("2021-01-01 06:00 +00:00  not published"));
+    assert!(report.contains("2021-01-01 07:00 +00:00  download failed"));
+}
+
+#[test]
+fn substituted_frames_are_reported_as_gaps() {
+    let server = MockServer::start();
+    for hour in (0..24).filter(|hour| *hour != 3 && *hour != 15) {
+        let mut image = GIF_BYTES.to_vec();
+        image.push(hour as u8);
+        server.add_frame(SITE, IMAGE_TYPE, frame_time(hour), Behaviour::Image(image));
+    }
+    let directory = scratch_directory("substituted-archive");
+    fs::create_dir_all(&directory).unwrap();
+    let outages = directory.join("outages.csv");
+    fs::write(&outages, "ATL,2021-01-01T15:00,2021-01-01T15:00\n").unwrap();
+
+    let output = run(&server, &directory, &["--outages", outages.to_str().unwrap()]);
+
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("24 requested, 22 downloaded, 1 missing during known outages, 0 not published, 1 substituted by a neighbouring image, 0 failed"));
+    assert!(report.contains("2021-01-01 03:00 +00:00  substituted by a neighbouring image"));
+    assert!(report.contains("2021-01-01 15:00 +00:00  radar outage"));
+}
+
+#[test]
+fn substitutes_are_placed_inside_known_outages() {
+    let server = MockServer::start();
+    for hour in (0..24).filter(|hour| *hour != 4 && *hour != 5) {
+        let mut image = GIF_BYTES.to_vec();
+        image.push(hour as u8);
+        server.add_frame(SITE, IMAGE_TYPE, frame_time(hour), Behaviour::Image(image));
+    }
+    let directory = scratch_directory("substitutes-in-outages");
+    fs::create_dir_all(&directory).unwrap();
+    let outages = directory.join("outages.csv");
+    fs::write(&outages, "ATL,2021-01-01T04:00,2021-01-01T05:00\n").unwrap();
+
+    // 04:00 is answered with the 03:00 image and 05:00 with the 06:00 image.
+    let output = run(&server, &directory, &["--outages", outages.to_str().unwrap()]);
+
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("24 requested, 22 downloaded, 2 missing during known outages, 0 not published, 0 substituted by a neighbouring image, 0 failed"));
+    assert!(report.contains("2021-01-01 05:00 +00:00  radar outage"));
+    assert!(!report.contains("2021-01-01 06:00 +00:00"));
+}
+
+#[test]
+fn repeats_within_the_publication_interval_are_not_gaps() {
+    let server = MockServer::start();
+    for hour in 0..24 {
+        let mut image = GIF_BYTES.to_vec();
+        image.push(hour as u8);
+        server.add_frame(SITE, IMAGE_TYPE, frame_time(hour), Behaviour::Image(image));
+    }
+    let directory = scratch_directory("repeats");
+
+    let output = run(&server, &directory, &["--interval", "10"]);
+
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("144 requested, 144 downloaded, 0 missing during known outages, 0 not published, 0 substituted by a neighbouring image, 0 failed"));
+}
+
+#[test]
+fn outages_ending_before_they_start_are_refused() {
+    let server = MockServer::start();
+    let directory = scratch_directory("inverted-outages");
+    fs::create_dir_all(&directory).unwrap();
+    let outages = directory.join("outages.csv");
+    fs::write(&outages, "ATL,2021-01-01T05:00,2021-01-01T03:00\n").unwrap();
+
+    assert!(!run_for(&server, &directory, IMAGE_TYPE, &["--outages", outages.to_str().unwrap()]).status.success());
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn impossible_site_and_image_type_combinations_are_refused() {
+    let server = MockServer::start();
+    let directory = scratch_directory("impossible");
+
+    assert!(!run_for(&server, &directory, "COMP_PRECIPET_RAIN_WEATHEROFFICE", &[]).status.success());
+    assert!(server.requests().is_empty());
+
+    assert!(run_for(&server, &directory, "COMP_PRECIPET_RAIN_WEATHEROFFICE", &["--skip-registry-check"]).status.success());
+    assert!(!server.requests().is_empty());
+}
+
+#[test]
+fn frames_on_other_volumes_are_not_requested_again() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Image(GIF_BYTES.to_vec()));
+    let directory = scratch_directory("volume-current");
+    let volume = scratch_directory("volume-previous");
+    fs::create_dir_all(&volume).unwrap();
+    fs::write(volume.join("ATL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T05-00.gif"), GIF_BYTES).unwrap();
+
+    run(&server, &directory, &["--volume", volume.to_str().unwrap()]);
+
+    assert!(server.requests().iter().all(|request| !request.contains("time=202101010500")));
+    assert!(!stored_files(&directory).contains(&"ATL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T05-00.gif".to_owned()));
+}
+
+#[test]
+fn missing_volumes_are_refused() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Image(GIF_BYTES.to_vec()));
+    let directory = scratch_directory("volume-present");
+    let volume = scratch_directory("volume-unmounted");
+
+    assert!(!run_for(&server, &directory, IMAGE_TYPE, &["--volume", volume.to_str().unwrap()]).status.success());
+    assert!(server.requests().is_empty());
+
+    let status = binary()
+        .args(["snapshot", "create", "--directory"]).arg(&volume)
+        .arg("--output").arg(directory.join("snapshot"))
+        .status()
+        .expect("Failed to run downloader.");
+    assert!(!status.success());
+}
+
+#[test]
+fn stalled_transfers_are_retried_then_abandoned() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Stall);
+    for hour in 1..24 {
+        server.add_frame(SITE, IMAGE_TYPE, frame_time(hour), Behaviour::Image(GIF_BYTES.to_vec()));
+    }
+    let directory = scratch_directory("stalled");
+
+    run(&server, &directory, &["--min-speed", "1kB/s@1s", "--stall-retries", "1"]);
+
+    assert!(!stored_files(&directory).contains(&"ATL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T00-00.gif".to_owned()));
+    assert_eq!(stored_files(&directory).len(), 23);
+    let first_frame_requests = server.requests().iter().filter(|request| request.contains("time=202101010000")).count();
+    assert_eq!(first_frame_requests, 2);
+}
+
+#[test]
+fn silent_servers_are_retried_then_abandoned() {
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Silent);
+    for hour in 1..24 {
+        server.add_frame(SITE, IMAGE_TYPE, frame_time(hour), Behaviour::Image(GIF_BYTES.to_vec()));
+    }
+    let directory = scratch_directory("silent");
+
+    run(&server, &directory, &["--min-speed", "1kB/s@1s", "--stall-retries", "1"]);
+
+    assert!(!stored_files(&directory).contains(&"ATL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T00-00.gif".to_owned()));
+    assert_eq!(stored_files(&directory).len(), 23);
+    let first_frame_requests = server.requests().iter().filter(|request| request.contains("time=202101010000")).count();
+    assert_eq!(first_frame_requests, 2);
+}
+
+#[test]
+fn snapshots_record_frames_added_removed_and_changed() {
+    let frame = |hour: u32| format!("ATL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T{:02}-00.gif", hour);
+    let (kept, removed, changed, added) = (frame(0), frame(1), frame(2), frame(3));
+
+    let directory = scratch_directory("snapshot-archive");
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join(&kept), GIF_BYTES).unwrap();
+    fs::write(directory.join(&removed), GIF_BYTES).unwrap();
+    fs::write(directory.join(&changed), GIF_BYTES).unwrap();
+
+    let manifests = scratch_directory("snapshot-manifests");
+    fs::create_dir_all(&manifests).unwrap();
+    let snapshot = |output: &Path| {
+        binary()
+            .args(["snapshot", "create", "--directory"]).arg(&directory)
+            .arg("--output").arg(output)
+            .status()
+            .expect("Failed to run downloader.")
+            .success()
+    };
+
+    assert!(snapshot(&manifests.join("earlier")));
+    fs::remove_file(directory.join(&removed)).unwrap();
+    fs::write(directory.join(&changed), b"GIF89a").unwrap();
+    fs::write(directory.join(&added), GIF_BYTES).unwrap();
+    // Files kept alongside the frames are not part of the archive state.
+    fs::write(directory.join("outages.csv"), "ATL,2021-01-01T00:00,2021-01-01T05:00\n").unwrap();
+    assert!(snapshot(&directory.join("manifest")));
+    assert!(snapshot(&manifests.join("later")));
+
+    let output = binary()
+        .args(["snapshot", "diff"]).arg(manifests.join("earlier")).arg(manifests.join("later"))
+        .output()
+        .expect("Failed to run downloader.");
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains(&format!("+ {}", added)));
+    assert!(report.contains(&format!("- {}", removed)));
+    assert!(report.contains(&format!("~ {}", changed)));
+    assert!(!report.contains(&kept));
+    assert!(report.contains("Added (1):"));
+
+    let manifest = fs::read_to_string(manifests.join("later")).unwrap();
+    fs::write(manifests.join("edited"), manifest.replace(&added, &frame(4))).unwrap();
+    fs::write(manifests.join("unrelated"), "name\t1\tabc\n").unwrap();
+    for name in &["edited", "unrelated"] {
+        let status = binary()
+            .args(["snapshot", "diff"]).arg(manifests.join("earlier")).arg(manifests.join(name))
+            .status()
+            .expect("Failed to run downloader.");
+        assert!(!status.success());
+    }
+
+    // A tab in a frame name would split its manifest line.
+    fs::write(directory.join("AT\tL_PRECIPET_RAIN_WEATHEROFFICE_2021-01-01T05-00.gif"), GIF_BYTES).unwrap();
+    assert!(!snapshot(&manifests.join("tabbed")));
+}
+
+#[test]
+fn cadence_is_inferred_from_image_contents_and_used_for_planning() {
+    let directory = scratch_directory("cadence");
+    fs::create_dir_all(&directory).unwrap();
+    let write = |name: &str, content: u8| {
+        let mut image = GIF_BYTES.to_vec();
+        image.push(content);
+        fs::write(directory.join(format!("ATL_PRECIPET_RAIN_WEATHEROFFICE_{}.gif", name)), image).unwrap();
+    };
+    // Requested every 10 minutes, but a new image only appears every hour.
+    for hour in 0..3 {
+        for minute in (0..60).step_by(10) {
+            write(&format!("2020-12-01T{:02}-{:02}", hour, minute), hour);
+        }
+    }
+    // A new image on every request, every 10 minutes.
+    for (content, minute) in [0, 10, 20].iter().enumerate() {
+        write(&format!("2021-01-01T00-{:02}", minute), content as u8);
+    }
+    // A new image on every request, every hour: it may have been published more often.
+    for hour in 0..3 {
+        write(&format!("2021-02-01T{:02}-00", hour), hour);
+    }
+
+    let output = binary()
+        .args(["cadence", "--site", SITE, "--directory"]).arg(&directory)
+        .output()
+        .expect("Failed to run downloader.");
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("2020-12-01 00:00 +00:00 to 2020-12-01 02:50 +00:00  every 60 minutes  (3 images, 18 files)"));
+    assert!(report.contains("2021-01-01 00:00 +00:00 to 2021-01-01 00:20 +00:00  every 10 minutes  (3 images, 3 files)"));
+    assert!(report.contains("2021-02-01 00:00 +00:00 to 2021-02-01 02:00 +00:00  every 60 minutes or more often  (3 images, 3 files)"));
+
+    let server = MockServer::start();
+    server.add_frame(SITE, IMAGE_TYPE, frame_time(0), Behaviour::Image(GIF_BYTES.to_vec()));
+
+    run(&server, &directory, &[]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 24 * 6 - 3);
+    assert!(requests.iter().any(|request| request.contains("time=202101010030")));
+
+    // The unresolved month is probed every 10 minutes on its first day, then requested hourly.
+    // Images repeated within an hour on the probe day are expected, not substitutes.
+    let server = MockServer::start();
+    for day in 1..3 {
+        for hour in 0..24 {
+            let mut image = GIF_BYTES.to_vec();
+            image.push(if day == 1 { hour } else { 24 + hour });
+            let time = NaiveDate::from_ymd_opt(2021, 2, day).unwrap().and_hms_opt(hour.into(), 0, 0).unwrap();
+            server.add_frame(SITE, IMAGE_TYPE, time, Behaviour::Image(image));
+        }
+    }
+
+    let output = binary()
+        .args(["--site", SITE, "--image-type", IMAGE_TYPE])
+        .args(["--start-year", "2021", "--start-month", "2", "--start-day", "1"])
+        .args(["--end-year", "2021", "--end-month", "2", "--end-day", "2"])
+        .arg("--directory").arg(&directory)
+        .arg("--base-url").arg(server.base_url())
+        .output()
+        .expect("Failed to run downloader.");
+    assert!(output.status.success());
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 24 * 6 - 3 + 24);
+    assert!(requests.iter().any(|request| request.contains("time=202102010010")));
+    assert!(!requests.iter().any(|request| request.contains("time=202102020010")));
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("165 requested, 165 downloaded, 0 missing during known outages, 0 not published, 0 substituted by a neighbouring image, 0 failed"));
+}